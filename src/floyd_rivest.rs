@@ -1,23 +1,255 @@
-use std::cmp::Ordering::{self, Greater, Less};
+use std::cmp::Ordering::{self, Equal, Greater, Less};
 use std::{cmp, ptr};
 
+use super::mom;
+
 pub fn select<T, F>(array: &mut [T], k: usize, mut f: F)
 where
     F: FnMut(&T, &T) -> Ordering,
 {
     let r = array.len() - 1;
-    select_(array, &mut f, 0, r, k)
+    let limit = WORK_FACTOR * array.len();
+    let mut budget = 0;
+    select_(array, &mut f, 0, r, k, &mut budget, limit)
 }
 
 const A: usize = 600;
 const B: f32 = 0.5;
 
-fn select_<T, F>(array: &mut [T], cmp: &mut F, mut left: usize, mut right: usize, k: usize)
+// Once the total number of elements scanned across all partitions
+// exceeds `WORK_FACTOR * n`, the Floyd-Rivest sampling step is
+// abandoned in favour of the guaranteed-linear median-of-medians
+// pivot (see the `*budget > limit` branch below). This bounds the
+// worst case at O(n) even for adversarial inputs that would
+// otherwise defeat the sampling heuristic.
+const WORK_FACTOR: usize = 5;
+
+// Test-only instrumentation: counts how many times the median-of-medians
+// fallback below has actually fired, so a test can assert the
+// guaranteed-linear-time path is genuinely exercised rather than just
+// trusting that an input "looks" adversarial.
+#[cfg(test)]
+static FALLBACK_HITS: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(test)]
+fn fallback_hits() -> usize {
+    FALLBACK_HITS.load(::std::sync::atomic::Ordering::SeqCst)
+}
+
+// Size (in elements) of the blocks classified against the pivot in
+// `partition_in_blocks`. Matches the block size pdqsort/BlockQuicksort
+// use: large enough to amortise the loop overhead, small enough that
+// the two `u8` offset buffers stay cache-resident.
+const BLOCK: usize = 128;
+
+// Branchless block partitioning, ported from pdqsort's
+// `partition_in_blocks`. `[i, j]` is scanned in fixed-size blocks from
+// both ends; each element's comparison against `t` is recorded as an
+// offset into a small buffer *without* branching on the result (the
+// store always happens, only whether the write cursor advances
+// depends on the comparison), and matched out-of-place pairs from the
+// two ends are swapped in a tight, predictable loop. This trades a
+// branch misprediction per element (the classic two-pointer partition
+// in `select_`) for a data dependency the CPU can pipeline freely, which is
+// the main hot loop for large slices.
+//
+// Returns the partition boundary: every element at or before the
+// returned index is `<= t`, everything after is `>= t`.
+unsafe fn partition_in_blocks<T, F>(
+    arr_ptr: *mut T,
+    mut i: usize,
+    mut j: usize,
+    t: &T,
+    cmp: &mut F,
+) -> usize
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut offsets_l = [0u8; BLOCK];
+    let mut start_l = 0usize;
+    let mut end_l = 0usize;
+    let mut block_l = BLOCK;
+
+    let mut offsets_r = [0u8; BLOCK];
+    let mut start_r = 0usize;
+    let mut end_r = 0usize;
+    let mut block_r = BLOCK;
+
+    loop {
+        let width = j + 1 - i;
+        let is_done = width <= 2 * BLOCK;
+
+        if is_done {
+            // Down to the last blocks: shrink whichever side still
+            // has a full-sized block pending so the two meet exactly
+            // at the end of `[i, j]`.
+            let mut rem = width;
+            if start_l < end_l || start_r < end_r {
+                rem -= BLOCK;
+            }
+            if start_l < end_l {
+                block_r = rem;
+            } else if start_r < end_r {
+                block_l = rem;
+            } else {
+                block_l = rem / 2;
+                block_r = rem - block_l;
+            }
+        }
+
+        if start_l == end_l {
+            start_l = 0;
+            end_l = 0;
+            for b in 0..block_l {
+                offsets_l[end_l] = b as u8;
+                end_l += (cmp(&*arr_ptr.add(i + b), t) != Less) as usize;
+            }
+        }
+
+        if start_r == end_r {
+            start_r = 0;
+            end_r = 0;
+            for b in 0..block_r {
+                offsets_r[end_r] = b as u8;
+                end_r += (cmp(&*arr_ptr.add(j.wrapping_sub(b)), t) != Greater) as usize;
+            }
+        }
+
+        let count = cmp::min(end_l - start_l, end_r - start_r);
+        for idx in 0..count {
+            let l = i + offsets_l[start_l + idx] as usize;
+            let r = j - offsets_r[start_r + idx] as usize;
+            ptr::swap(arr_ptr.add(l), arr_ptr.add(r));
+        }
+        start_l += count;
+        start_r += count;
+
+        if start_l == end_l {
+            i += block_l;
+        }
+        if start_r == end_r {
+            j -= block_r;
+        }
+
+        if is_done {
+            break;
+        }
+    }
+
+    // At most one side can have leftover offsets once the loop above
+    // has processed the whole `[i, j]` range (the block-size
+    // bookkeeping above guarantees the two sides meet evenly
+    // otherwise). Draining the leftover side swaps every remaining
+    // misplaced element across to its counterpart, which finishes the
+    // partition outright and gives the boundary directly.
+    if start_l < end_l {
+        while start_l < end_l {
+            end_l -= 1;
+            let l = i + offsets_l[end_l] as usize;
+            ptr::swap(arr_ptr.add(l), arr_ptr.add(j));
+            j = j.wrapping_sub(1);
+        }
+        j
+    } else {
+        while start_r < end_r {
+            end_r -= 1;
+            let r = j - offsets_r[end_r] as usize;
+            ptr::swap(arr_ptr.add(i), arr_ptr.add(r));
+            i += 1;
+        }
+        i - 1
+    }
+}
+
+// Dutch-national-flag partitioning of `[left, right]` around the
+// pivot `t`, which sits at `t_idx` (one of `left` or `right`).
+// Classifies every other element with a single pass of three
+// cursors: `i` walks the unclassified region, swapping an
+// out-of-place element to whichever of `lt`/`gt` it belongs past;
+// equal elements are simply stepped over, so they end up banded
+// together in the middle.
+//
+// Returns `(lt, gt)`, the inclusive bounds of the (now contiguous)
+// run of elements equal to `t` -- `select_` is done as soon as `k`
+// falls in this range, and otherwise only needs to recurse into
+// whichever side `k` falls on.
+unsafe fn three_way_partition<T, F>(
+    arr_ptr: *mut T,
+    left: usize,
+    right: usize,
+    t_idx: usize,
+    t: &T,
+    cmp: &mut F,
+) -> (usize, usize)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let (scan_left, scan_right) = if t_idx == left {
+        (left + 1, right)
+    } else {
+        (left, right - 1)
+    };
+
+    let mut lt = scan_left;
+    let mut i = scan_left;
+    let mut gt = scan_right;
+    while i <= gt {
+        let ord = cmp(&*arr_ptr.add(i), t);
+        if ord == Less {
+            ptr::swap(arr_ptr.add(lt), arr_ptr.add(i));
+            lt += 1;
+            i += 1;
+        } else if ord == Greater {
+            ptr::swap(arr_ptr.add(i), arr_ptr.add(gt));
+            if gt == 0 {
+                break;
+            }
+            gt -= 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    // The pivot itself was skipped by the scan above; fold it into
+    // the equal run by swapping it in from whichever end it started
+    // at, extending the run by exactly one slot. The elements within
+    // the `< t`/`> t` regions don't need to stay in their relative
+    // order, so a single swap (rather than shifting the whole
+    // region) is enough.
+    if t_idx == left {
+        ptr::swap(arr_ptr.add(left), arr_ptr.add(lt - 1));
+        (lt - 1, gt)
+    } else {
+        ptr::swap(arr_ptr.add(right), arr_ptr.add(gt + 1));
+        (lt, gt + 1)
+    }
+}
+
+fn select_<T, F>(
+    array: &mut [T],
+    cmp: &mut F,
+    mut left: usize,
+    mut right: usize,
+    k: usize,
+    budget: &mut usize,
+    limit: usize,
+)
 where
     F: FnMut(&T, &T) -> Ordering,
 {
     while right > left {
-        if right - left > A {
+        *budget += right - left + 1;
+
+        if *budget > limit {
+            // Guaranteed linear worst case: median-of-medians returns
+            // a pivot between the 30th and 70th percentile of the
+            // subrange, so the partition below discards at least
+            // ~30% of it no matter how adversarial `array` is.
+            #[cfg(test)]
+            FALLBACK_HITS.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+            let (idx, _) = mom::median_of_medians_by(&mut array[left..right + 1], &mut *cmp);
+            array.swap(left + idx, k);
+        } else if right - left > A {
             let n = (right - left + 1) as f32;
             let i = (k - left + 1) as f32;
             let z = n.ln();
@@ -30,7 +262,7 @@ where
             let new_left = cmp::max(left, inner as usize);
             let new_right = cmp::min(right, (inner + s) as usize);
 
-            select_(array, cmp, new_left, new_right, k)
+            select_(array, cmp, new_left, new_right, k, budget, limit)
         }
 
         let mut i = left + 1;
@@ -59,7 +291,34 @@ where
             }
         }
 
-        if i < j {
+        // Cheap duplicate probe: `i`/`j` above already stopped on the
+        // first element from each end that isn't strictly on its
+        // side, so if either is an exact match for the pivot there is
+        // likely a large equal run. Switch to a three-way partition
+        // so repeatedly re-scanning that run (e.g. selecting within
+        // an array of booleans) doesn't go quadratic.
+        let has_duplicate =
+            unsafe { cmp(&*arr_ptr.add(i), t) == Equal || cmp(&*arr_ptr.add(j), t) == Equal };
+
+        if has_duplicate {
+            let (lt, gt) = unsafe { three_way_partition(arr_ptr, left, right, t_idx, t, cmp) };
+            if lt <= k && k <= gt {
+                // `k` lands inside the equal-to-pivot run: already in
+                // its final place, nothing left to narrow down.
+                return;
+            } else if k < lt {
+                right = lt - 1;
+            } else {
+                left = gt + 1;
+            }
+            continue;
+        }
+
+        let mut j = if i < j && j + 1 - i > 2 * BLOCK {
+            // Large enough to be worth the fixed overhead of block
+            // partitioning; this is the hot loop for big slices.
+            unsafe { partition_in_blocks(arr_ptr, i, j, t, cmp) }
+        } else if i < j {
             // i < j, and i and j move toward each other, so this
             // assertion ensures that all indexing here is in-bounds.
             assert!(j < array.len());
@@ -81,7 +340,10 @@ where
                     }
                 }
             }
-        }
+            j
+        } else {
+            j
+        };
 
         if left == t_idx {
             array.swap(left, j);
@@ -98,12 +360,63 @@ where
     }
 }
 
+/// Like `select`, but for several targets at once: partitions `array`
+/// so that every index in `ks` ends up correctly placed.
+///
+/// `ks` must be sorted and contain distinct, in-bounds indices (the
+/// caller, `kths_by`, is responsible for checking this).
+pub fn select_many<T, F>(array: &mut [T], ks: &[usize], mut f: F)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if array.is_empty() || ks.is_empty() {
+        return;
+    }
+    let r = array.len() - 1;
+    let limit = WORK_FACTOR * array.len();
+    let mut budget = 0;
+    select_multi(array, &mut f, 0, r, ks, &mut budget, limit)
+}
+
+// Recursively narrows `[left, right]` down to every index in `ks`
+// (which must all lie within `[left, right]`). Selecting the middle
+// target with the ordinary single-target `select_` also partitions
+// the whole subrange around it, so the remaining targets split for
+// free into those left and those right of it; recursing on each half
+// with only its own, disjoint share of `ks` means the array is never
+// rescanned on behalf of a target that's already been resolved by an
+// earlier split.
+fn select_multi<T, F>(
+    array: &mut [T],
+    cmp: &mut F,
+    left: usize,
+    right: usize,
+    ks: &[usize],
+    budget: &mut usize,
+    limit: usize,
+)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if ks.is_empty() {
+        return;
+    }
+    debug_assert!(ks.iter().all(|&k| left <= k && k <= right));
+
+    let mid = ks.len() / 2;
+    let k = ks[mid];
+    select_(array, cmp, left, right, k, budget, limit);
+
+    select_multi(array, cmp, left, k.wrapping_sub(1), &ks[..mid], budget, limit);
+    select_multi(array, cmp, k + 1, right, &ks[mid + 1..], budget, limit);
+}
+
 #[cfg(test)]
 mod tests {
     use quickcheck::{self, TestResult};
     use rand::{Rng, XorShiftRng};
 
-    use super::select;
+    use super::{fallback_hits, select, select_many};
 
     #[test]
     fn qc() {
@@ -120,6 +433,25 @@ mod tests {
         quickcheck::quickcheck(run as fn(Vec<i32>, usize) -> TestResult)
     }
 
+    #[test]
+    fn qc_many() {
+        fn run(mut x: Vec<i32>, mut ks: Vec<usize>) -> TestResult {
+            if x.is_empty() {
+                return TestResult::discard();
+            }
+            ks.iter_mut().for_each(|k| *k %= x.len());
+            ks.sort();
+            ks.dedup();
+
+            select_many(&mut x, &ks, Ord::cmp);
+
+            let mut sorted = x.clone();
+            sorted.sort();
+            TestResult::from_bool(ks.iter().all(|&k| x[k] == sorted[k]))
+        }
+        quickcheck::quickcheck(run as fn(Vec<i32>, Vec<usize>) -> TestResult)
+    }
+
     #[test]
     fn smoke() {
         for k in 0..4 {
@@ -141,6 +473,83 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn block_partition_boundary() {
+        // Exercise the block-partitioning path in `select_` (active once
+        // a subrange's width exceeds `2 * BLOCK`), around and across
+        // that threshold.
+        for &n in &[250usize, 256, 257, 300, 600, 1_000] {
+            let v: Vec<i32> = (0..n as i32).rev().collect();
+            for k in (0..n).step_by(31) {
+                let mut w = v.clone();
+                select(&mut w, k, Ord::cmp);
+                assert_eq!(w[k], k as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn many_duplicates() {
+        // Low-cardinality inputs are the case three-way partitioning
+        // exists for: without it, `select_` would keep re-scanning a
+        // huge equal run and go quadratic.
+        let n = 200_000;
+        let v: Vec<i32> = (0..n as i32).map(|x| x % 2).collect();
+        for k in (0..n).step_by(9973) {
+            let mut w = v.clone();
+            select(&mut w, k, Ord::cmp);
+            assert_eq!(w[k], if k < n / 2 { 0 } else { 1 });
+        }
+
+        let all_same = vec![7i32; 10_000];
+        for &k in &[0, 1, 5_000, 9_999] {
+            let mut w = all_same.clone();
+            select(&mut w, k, Ord::cmp);
+            assert_eq!(w[k], 7);
+        }
+    }
+
+    #[test]
+    fn many_targets() {
+        let n = 1_000;
+        let v: Vec<i32> = (0..n as i32).rev().collect();
+        let mut sorted = v.clone();
+        sorted.sort();
+
+        // deciles, plus the first and last elements.
+        let ks: Vec<usize> = Some(0)
+            .into_iter()
+            .chain((0..10).map(|i| i * n / 10))
+            .chain(Some(n - 1))
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut w = v.clone();
+        select_many(&mut w, &ks, Ord::cmp);
+        for &k in &ks {
+            assert_eq!(w[k], sorted[k]);
+        }
+    }
+
+    #[test]
+    fn sorted_adversarial() {
+        // Reverse-sorted input (unlike ascending-sorted, which the
+        // Floyd-Rivest sampling formula handles just fine) actually
+        // defeats the sampling heuristic at this size, so it drives
+        // `select_`'s median-of-medians fallback; check it really fires
+        // rather than just hoping the input looks adversarial enough.
+        let n = 50_000;
+        let hits_before = fallback_hits();
+        for k in (0..n).step_by(3_701) {
+            let mut v: Vec<i32> = (0..n as i32).rev().collect();
+            select(&mut v, k, Ord::cmp);
+            assert_eq!(v[k], k as i32);
+        }
+        assert!(fallback_hits() > hits_before,
+                "median-of-medians fallback never fired for reverse-sorted input");
+    }
 }
 
 #[cfg(all(test, feature = "unstable"))]
@@ -148,4 +557,73 @@ mod benches {
     extern crate test;
 
     make_benches!(|m, mut v| super::select(&mut v, m, Ord::cmp));
+
+    // Directly compares `partition_in_blocks` against the scalar
+    // two-pointer partition it replaced for large slices, to
+    // substantiate the claim that block partitioning is worth its
+    // fixed overhead once a subrange is big enough to use it.
+    mod partition_in_blocks {
+        extern crate test;
+
+        use rand::{Rng, XorShiftRng};
+
+        use super::super::partition_in_blocks;
+
+        const N: usize = 100_000;
+
+        fn data() -> (Vec<i32>, i32) {
+            let v = XorShiftRng::new_unseeded().gen_iter::<i32>().take(N).collect::<Vec<_>>();
+            let t = v[N / 2];
+            (v, t)
+        }
+
+        // The scalar two-pointer partition `select_` used before block
+        // partitioning was introduced, kept here only for comparison.
+        fn scalar_partition(v: &mut [i32], t: i32) {
+            let (mut i, mut j) = (0, v.len() - 1);
+            while v[i] < t {
+                i += 1;
+            }
+            while v[j] > t {
+                j -= 1;
+            }
+            while i < j {
+                v.swap(i, j);
+                i += 1;
+                j -= 1;
+                while v[i] < t {
+                    i += 1;
+                }
+                while v[j] > t {
+                    j -= 1;
+                }
+            }
+        }
+
+        #[bench]
+        fn blocks(b: &mut test::Bencher) {
+            let (v, t) = data();
+            b.iter(|| {
+                let mut w = v.clone();
+                let (mut i, mut j) = (0, w.len() - 1);
+                while w[i] < t {
+                    i += 1;
+                }
+                while w[j] > t {
+                    j -= 1;
+                }
+                let mut cmp = |a: &i32, b: &i32| a.cmp(b);
+                unsafe { partition_in_blocks(w.as_mut_ptr(), i, j, &t, &mut cmp) }
+            });
+        }
+
+        #[bench]
+        fn scalar(b: &mut test::Bencher) {
+            let (v, t) = data();
+            b.iter(|| {
+                let mut w = v.clone();
+                scalar_partition(&mut w, t);
+            });
+        }
+    }
 }
@@ -77,6 +77,31 @@
 //! println!("{:?}'s field is close to the median of the fields",
 //!         order_stat::median_of_medians_by(&mut v, |x, y| x.0.cmp(&y.0)).1);
 //! ```
+//!
+//! If many order statistic queries need to be performed on the same
+//! data, `Index` amortises the cost of a single sort across all of
+//! them, and answers `rank` queries (the complement of `kth`: "how
+//! many elements are smaller than this value?") in `O(log n)`.
+//!
+//! ```rust
+//! let idx = order_stat::Index::new(&[4, 1, 3, 2, 0]);
+//!
+//! println!("the 2nd smallest element is {}", // 1
+//!          idx.select(1));
+//! println!("{} elements are smaller than 3", // 3
+//!          idx.rank(&3));
+//! ```
+//!
+//! If just a handful of order statistics are needed, rather than
+//! enough to justify building an `Index`, `kths` computes them all in
+//! one pass, reusing work across targets.
+//!
+//! ```rust
+//! let mut v = [10, 0, -10, 20, 5, -5];
+//!
+//! println!("the smallest, median and largest elements are {:?}",
+//!          order_stat::kths(&mut v, &[0, 3, 5])); // [-10, 5, 20]
+//! ```
 
 #![cfg_attr(all(test, feature = "unstable"), feature(test))]
 
@@ -93,6 +118,7 @@ mod benches;
 mod floyd_rivest;
 mod quickselect;
 mod mom;
+mod index;
 
 /// Compute the `k`th order statistic (`k`th smallest element) of
 /// `array` via the Floyd-Rivest Algorithm[1].
@@ -193,4 +219,65 @@ pub fn kth_by<T, F>(array: &mut [T], k: usize, cmp: F) -> &mut T
     &mut array[k]
 }
 
+/// Compute several order statistics of `array` at once, via repeated
+/// application of the Floyd-Rivest Algorithm (see `kth`).
+///
+/// `ks` is zero-indexed, like `kth`, and must be sorted with distinct
+/// elements. The return value is a vector of references into `array`,
+/// one per element of `ks`, in the same order as `ks`; `array` is
+/// mutated so that `array[k]` holds the `k`th order statistic for
+/// every `k` in `ks` simultaneously, with smaller elements before and
+/// larger after each one (exactly as a single `kth` call guarantees
+/// for its own `k`).
+///
+/// This sits between a handful of individual `kth` calls and sorting
+/// `array` outright: work done while resolving one target is reused
+/// by the others, so e.g. computing all the deciles of `array` is
+/// much cheaper than ten separate `kth` calls, and cheaper still than
+/// a full sort.
+///
+/// # Panics
+///
+/// If `ks` is not sorted with distinct elements, or if any element of
+/// `ks` is `>= array.len()`, `kths` panics.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut v = [10, 0, -10, 20, 5, -5];
+/// let kths = order_stat::kths(&mut v, &[0, 3, 5]);
+///
+/// assert_eq!(kths, [&-10, &5, &20]);
+/// ```
+pub fn kths<'a, T: Ord>(array: &'a mut [T], ks: &[usize]) -> Vec<&'a mut T> {
+    kths_by(array, ks, Ord::cmp)
+}
+
+/// Compute several order statistics of `array` at once, in the
+/// ordering defined by `cmp`.
+///
+/// See `kths` for more details. It is equivalent to
+/// `kths_by(array, ks, Ord::cmp)`.
+///
+/// # Panics
+///
+/// If `ks` is not sorted with distinct elements, or if any element of
+/// `ks` is `>= array.len()`, `kths_by` panics.
+pub fn kths_by<'a, T, F>(array: &'a mut [T], ks: &[usize], cmp: F) -> Vec<&'a mut T>
+    where F: FnMut(&T, &T) -> Ordering
+{
+    assert!(ks.windows(2).all(|w| w[0] < w[1]),
+            "order_stat::kths_by called with `ks` that is not sorted with distinct elements");
+    assert!(ks.iter().all(|&k| k < array.len()),
+            "order_stat::kths_by called with a k >= len = {}", array.len());
+
+    floyd_rivest::select_many(array, ks, cmp);
+
+    let ptr = array.as_mut_ptr();
+    // Safe: the assertions above guarantee `ks`'s elements are
+    // distinct and in-bounds, so these `&mut T`s never alias.
+    ks.iter().map(|&k| unsafe { &mut *ptr.add(k) }).collect()
+}
+
 pub use mom::{median_of_medians, median_of_medians_by};
+pub use index::Index;
@@ -53,6 +53,19 @@ pub fn median_of_medians<T: Ord>(array: &mut [T]) -> (usize, &mut T) {
 pub fn median_of_medians_by<T, F>(array: &mut [T], mut cmp: F) -> (usize, &mut T)
     where F: FnMut(&T, &T) -> Ordering
 {
+    // Dispatch through a trait object from here on down: `select_`
+    // (in `floyd_rivest`) calls back into this function as its
+    // guaranteed-linear fallback, and this function calls back into
+    // `kth_by` in turn, so without erasing `F` here that mutual
+    // recursion would grow a fresh `&mut &mut ...` closure type on
+    // every bounce and blow the compiler's recursion limit.
+    median_of_medians_dyn(array, &mut cmp)
+}
+
+fn median_of_medians_dyn<'a, T>(
+    array: &'a mut [T],
+    cmp: &mut dyn FnMut(&T, &T) -> Ordering,
+) -> (usize, &'a mut T) {
     if array.len() < 5 {
         let median = array.len() / 2;
         return (median, super::kth_by(array, median, cmp))
@@ -62,7 +75,14 @@ pub fn median_of_medians_by<T, F>(array: &mut [T], mut cmp: F) -> (usize, &mut T
         let start = 5 * i;
         let trailing = array.len() - start;
         let idx = if trailing < 5 {
-            let elem = super::kth_by(&mut array[start..], trailing / 2, &mut cmp) as *mut _ as usize;
+            // The lower median (not `trailing / 2`, which picks the
+            // *upper* median for even-sized trailing groups): a group
+            // of 5 contributes its middle element, which has 2 elements
+            // on each side; no element splits an even-sized partial
+            // group evenly, and the lower median, with at least as
+            // many elements above it as below, is the choice that
+            // keeps the overall 30th/70th-percentile guarantee.
+            let elem = super::kth_by(&mut array[start..], (trailing - 1) / 2, &mut *cmp) as *mut _ as usize;
 
             // compute the index of that element (zero sized types
             // don't matter what index they end up, they're all at the
@@ -70,7 +90,7 @@ pub fn median_of_medians_by<T, F>(array: &mut [T], mut cmp: F) -> (usize, &mut T
             let start = array.as_ptr() as usize;
             (elem - start) / cmp::max(1, mem::size_of::<T>())
         } else {
-            start + median5(&array[start..start+5], &mut cmp)
+            start + median5(&array[start..start+5], cmp)
         };
         array.swap(i, idx);
     }
@@ -78,9 +98,7 @@ pub fn median_of_medians_by<T, F>(array: &mut [T], mut cmp: F) -> (usize, &mut T
     (idx, super::kth_by(&mut array[..num_medians], idx, cmp))
 }
 
-fn median5<T, F>(array: &[T], cmp: &mut F) -> usize
-    where F: FnMut(&T, &T) -> Ordering
-{
+fn median5<T>(array: &[T], cmp: &mut dyn FnMut(&T, &T) -> Ordering) -> usize {
     use std::mem;
 
     let array = array;
@@ -157,6 +175,38 @@ mod tests {
         let mut v = [0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 0, 0, 0, 0];
         assert_eq!(*median_of_medians(&mut v).1, 0)
     }
+
+    // Directly checks the 30th/70th-percentile guarantee itself (not
+    // just the end-to-end answer) on inputs whose last group of 5 is
+    // short and skewed: `median_of_medians([0, 0, 0, 0, 0, 0, 1])` used
+    // to return `1`, the array's unique maximum, because the partial
+    // trailing group of 2 picked its *upper* rather than lower median.
+    fn assert_within_bounds(v: &[i32]) {
+        let mut x = v.to_vec();
+        let (_, &mut median) = median_of_medians(&mut x);
+        x.sort();
+
+        let thirty = x.len() * 3 / 10;
+        let seventy = cmp::min((x.len() * 7 + 9) / 10, x.len() - 1);
+        assert!(x[thirty] <= median && median <= x[seventy],
+                "{:?}: median {} not within [{}, {}]", v, median, x[thirty], x[seventy]);
+    }
+
+    #[test]
+    fn skewed_short_trailing_group() {
+        // lengths 6, 7, 8, 9 give trailing groups of size 1, 2, 3, 4
+        // respectively; skew each heavily towards 0 so a bad pick for
+        // the trailing group's representative is visible in the bound.
+        for &n in &[6, 7, 8, 9] {
+            let mut v = vec![0; n];
+            *v.last_mut().unwrap() = 1;
+            assert_within_bounds(&v);
+
+            let mut v = vec![1; n];
+            v[0] = 0;
+            assert_within_bounds(&v);
+        }
+    }
 }
 
 #[cfg(all(test, feature = "unstable"))]